@@ -1,38 +1,70 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::process::id;
 
-use crate::project::Plane;
+use crate::project::{Plane, Project};
+
+/// A fully-evaluated CAD project, as reconstructed by replaying operations.
+pub type State = Project;
+
+/// Number of most-recent snapshots always retained by the eviction policy.
+const SNAPSHOT_KEEP_RECENT: usize = 4;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpLog {
     commits: Vec<Commit>,
+    // Derived reachability index; rebuilt from `commits`, never serialized.
+    #[serde(skip, default)]
+    index: CommitGraph,
 }
 
 impl OpLog {
     pub fn new() -> Self {
-        Self { commits: vec![] }
+        Self {
+            commits: vec![],
+            index: CommitGraph::default(),
+        }
     }
 
     pub fn init(&mut self) {
         let creation_commit = Commit::init();
+        self.index.insert(&creation_commit);
         self.commits.push(creation_commit);
     }
 
-    pub fn append(&mut self, parent: &Sha, operation: Operation) -> Commit {
+    pub fn append(&mut self, parents: Vec<Sha>, operation: Operation) -> Commit {
         let op_hash = operation.hash();
-        let parent = parent.clone();
         let new_commit = Commit {
-            id: id_from_op_and_parent(&operation, &parent),
+            id: id_from_op_and_parents(&operation, &parents),
             operation,
             content_hash: op_hash,
-            parent,
+            parents,
         };
+        self.index.insert(&new_commit);
         self.commits.push(new_commit.clone());
         new_commit
     }
 
+    /// Borrow the reachability index (generation numbers plus parent and child
+    /// pointers). It keeps no binary-lifting ancestor tables: ancestry queries
+    /// walk parent edges. After deserializing an `OpLog` the index is empty;
+    /// call [`OpLog::rebuild_index`] to repopulate it.
+    pub fn index(&self) -> &CommitGraph {
+        &self.index
+    }
+
+    /// Rebuild the index from scratch by inserting every commit in stored
+    /// (topological) order. Needed after deserialization, since the index is
+    /// `#[serde(skip)]`.
+    pub fn rebuild_index(&mut self) {
+        self.index = CommitGraph::default();
+        for commit in &self.commits {
+            self.index.insert(commit);
+        }
+    }
+
     pub fn last(&self) -> Option<Commit> {
         match self.commits.last() {
             Some(commit) => Some(commit.clone()),
@@ -43,19 +75,189 @@ impl OpLog {
     pub fn get_length(&self) -> usize {
         self.commits.len()
     }
+
+    /// Verify the integrity of the whole log: every commit's recomputed id must
+    /// match its stored id, and every referenced parent must exist. On failure
+    /// returns the id of the first offending commit, so a tampered serialized
+    /// log can be rejected up front instead of being silently accepted.
+    pub fn verify_chain(&self) -> Result<(), Sha> {
+        let ids: HashSet<&Sha> = self.commits.iter().map(|c| &c.id).collect();
+        for commit in &self.commits {
+            if !commit.verify() {
+                return Err(commit.id.clone());
+            }
+            for parent in &commit.parents {
+                if !ids.contains(parent) {
+                    return Err(commit.id.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fold every commit id, together with its parents, into a single Merkle
+    /// root over the log in topological order. Two users can compare one root
+    /// hash to know their histories are byte-for-byte identical.
+    pub fn merkle_root(&self) -> Sha {
+        let mut acc = String::new();
+        for commit in &self.commits {
+            let mut hasher = Sha256::new();
+            hasher.update(acc.as_bytes());
+            hasher.update(commit.id.as_bytes());
+            for parent in &commit.parents {
+                hasher.update(parent.as_bytes());
+            }
+            acc = format!("{:x}", hasher.finalize());
+        }
+        acc
+    }
 }
 
-fn id_from_op_and_parent(operation: &Operation, parent: &Sha) -> Sha {
+fn id_from_op_and_parents(operation: &Operation, parents: &[Sha]) -> Sha {
     let h = operation.hash();
     let mut hasher = Sha256::new();
-    hasher.update(format!("{h}-{parent}").as_bytes());
+    hasher.update(format!("{h}-{}", parents.join(",")).as_bytes());
     format!("{:x}", hasher.finalize())
 }
 
+/// One node of the [`CommitGraph`]: its generation number and both the
+/// backward parent and forward child pointers, so reachability can be walked
+/// in either direction over the full DAG.
+#[derive(Debug, Clone, Default)]
+struct CommitEntry {
+    /// 0 for the init commit, `1 + max(parent generations)` otherwise. Because
+    /// a parent always has a strictly smaller generation, the generation bounds
+    /// ancestry: `a` can only be an ancestor of `b` when `gen(a) <= gen(b)`,
+    /// which lets reachability walks prune whole branches.
+    generation: u32,
+    /// SHAs this commit names as parents (none for the init commit, two for a
+    /// merge, one otherwise).
+    parents: Vec<Sha>,
+    /// SHAs of the commits that name this commit as a parent.
+    children: Vec<Sha>,
+}
+
+/// A commit-graph style index over [`OpLog`] that answers reachability and
+/// common-ancestor queries over the full branching DAG. Each commit carries a
+/// generation number (its longest distance from the init commit); since a
+/// parent's generation is always smaller, generations let ancestor walks prune
+/// branches that cannot reach the target and let `common_ancestor` pick the
+/// lowest merge base by generation. The merge feature and cherry-pick conflict
+/// detection consult this index rather than re-deriving ancestry ad hoc.
+#[derive(Debug, Clone, Default)]
+pub struct CommitGraph {
+    entries: HashMap<Sha, CommitEntry>,
+}
+
+impl CommitGraph {
+    /// Incrementally fold one commit into the index. Parents must already be
+    /// present, which holds because commits are appended in topological order.
+    fn insert(&mut self, commit: &Commit) {
+        let generation = commit
+            .parents
+            .iter()
+            .filter_map(|p| self.entries.get(p).map(|e| e.generation))
+            .max()
+            .map(|g| g + 1)
+            .unwrap_or(0);
+
+        for parent in &commit.parents {
+            if let Some(entry) = self.entries.get_mut(parent) {
+                entry.children.push(commit.id.clone());
+            }
+        }
+
+        self.entries.insert(
+            commit.id.clone(),
+            CommitEntry {
+                generation,
+                parents: commit.parents.clone(),
+                children: vec![],
+            },
+        );
+    }
+
+    /// Generation number of `sha`, if it is indexed.
+    pub fn generation(&self, sha: &Sha) -> Option<u32> {
+        self.entries.get(sha).map(|e| e.generation)
+    }
+
+    /// Commits that name `sha` as a parent.
+    pub fn children(&self, sha: &Sha) -> &[Sha] {
+        self.entries
+            .get(sha)
+            .map(|e| e.children.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Every ancestor of `sha` (inclusive), following all parent edges.
+    fn ancestors_inclusive(&self, sha: &Sha) -> HashSet<Sha> {
+        let mut set = HashSet::new();
+        let mut stack = vec![sha.clone()];
+        while let Some(node) = stack.pop() {
+            if set.insert(node.clone()) {
+                if let Some(entry) = self.entries.get(&node) {
+                    stack.extend(entry.parents.iter().cloned());
+                }
+            }
+        }
+        set
+    }
+
+    /// True when `a` is an ancestor of (or equal to) `b`, following every
+    /// parent edge — including a merge's second parent. The generation bound
+    /// lets the walk skip any commit that is too shallow to be `a`.
+    pub fn is_ancestor(&self, a: &Sha, b: &Sha) -> bool {
+        let (Some(ga), Some(gb)) = (self.generation(a), self.generation(b)) else {
+            return false;
+        };
+        if ga > gb {
+            return false;
+        }
+        let mut visited = HashSet::new();
+        let mut stack = vec![b.clone()];
+        while let Some(node) = stack.pop() {
+            if &node == a {
+                return true;
+            }
+            if !visited.insert(node.clone()) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&node) {
+                for parent in &entry.parents {
+                    if self.generation(parent).is_some_and(|g| g >= ga) {
+                        stack.push(parent.clone());
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Lowest common ancestor of `a` and `b` over the full DAG: the commit
+    /// reachable from both with the greatest generation, i.e. the merge base a
+    /// three-way merge would diff against. Returns `None` if either SHA is not
+    /// indexed.
+    pub fn common_ancestor(&self, a: &Sha, b: &Sha) -> Option<Sha> {
+        self.generation(a)?;
+        self.generation(b)?;
+        let ancestors_a = self.ancestors_inclusive(a);
+        self.ancestors_inclusive(b)
+            .into_iter()
+            .filter(|sha| ancestors_a.contains(sha))
+            .max_by_key(|sha| self.generation(sha).unwrap_or(0))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EvolutionLog {
     pub cursor: Sha,
+    pub branches: HashMap<String, Sha>,
     pub oplog: OpLog, // TODO: work out the lifetimes here so that we can have multiple evolutionLogs at once?
+    // Replay cache of evaluated project states keyed by the commit they
+    // correspond to; rebuildable, so never serialized.
+    #[serde(skip, default)]
+    snapshots: HashMap<Sha, State>,
 }
 
 impl EvolutionLog {
@@ -64,15 +266,67 @@ impl EvolutionLog {
         ol.init();
         Self {
             cursor: ol.last().unwrap().id.clone(),
+            branches: HashMap::new(),
             oplog: ol,
+            snapshots: HashMap::new(),
         }
     }
 
     pub fn append(&mut self, operation: Operation) -> Sha {
-        self.cursor = self.oplog.append(&self.cursor, operation).id;
+        let parent = self.cursor.clone();
+        self.cursor = self.oplog.append(vec![parent.clone()], operation).id;
+        // Any branch ref that pointed at the commit we just built on follows the
+        // cursor forward, so a named branch tracks new work built on its tip.
+        // Every such ref moves, not only the one last checked out.
+        for target in self.branches.values_mut() {
+            if *target == parent {
+                *target = self.cursor.clone();
+            }
+        }
         self.cursor.clone()
     }
 
+    /// Create a named branch ref pointing at the current cursor. Combined with
+    /// `checkout`/`checkout_branch`, this lets divergent design exploration fork
+    /// off any SHA: each subsequent `append` advances every ref that points at
+    /// the commit it builds on. Refs created on the same commit therefore move
+    /// together; no record is kept of which ref is checked out.
+    pub fn branch(&mut self, name: &str) {
+        self.branches.insert(name.to_owned(), self.cursor.clone());
+    }
+
+    /// Move the cursor onto the commit a named branch points at, so later
+    /// `append`s extend that branch.
+    pub fn checkout_branch(&mut self, name: &str) -> Result<(), String> {
+        match self.branches.get(name) {
+            Some(sha) => {
+                self.cursor = sha.clone();
+                Ok(())
+            }
+            None => Err(format!("branch '{}' not found", name)),
+        }
+    }
+
+    /// Reconcile two histories by recording a merge commit with both SHAs as
+    /// parents. The merge base is looked up through the reachability index and
+    /// recorded as the resolution, and the cursor is moved onto the new merge
+    /// commit.
+    pub fn merge(&mut self, a: Sha, b: Sha) -> Result<Sha, String> {
+        let index = self.oplog.index();
+        for sha in [&a, &b] {
+            if index.generation(sha).is_none() {
+                return Err(format!("SHA {} not found in oplog", sha));
+            }
+        }
+        let resolution = index.common_ancestor(&a, &b).unwrap_or_default();
+        let operation = Operation::Merge {
+            parents: vec![a.clone(), b.clone()],
+            resolution,
+        };
+        self.cursor = self.oplog.append(vec![a, b], operation).id;
+        Ok(self.cursor.clone())
+    }
+
     pub fn pretty_print(&self) {
         for commit in &self.oplog.commits {
             println!("{}", commit.pretty_print());
@@ -81,24 +335,222 @@ impl EvolutionLog {
 
     pub fn checkout(&mut self, sha: Sha) -> Result<(), String> {
         // check that the sha exists in the oplog before doing this
-        for commit in &self.oplog.commits {
-            if commit.id == sha {
-                self.cursor = sha;
-                return Ok(());
-            }
+        if self.oplog.index().generation(&sha).is_none() {
+            return Err(format!("SHA {} not found in oplog", sha));
         }
-        Err(format!("SHA {} not found in oplog", sha))
+        self.cursor = sha;
+        Ok(())
     }
 
     pub fn cherry_pick(&mut self, sha: Sha) -> Result<(), String> {
         // check that the sha exists in the oplog before doing this
+        let operation = match self.oplog.commits.iter().find(|c| c.id == sha) {
+            Some(commit) => commit.operation.clone(),
+            None => return Err(format!("SHA {} not found in oplog", sha)),
+        };
+        // nothing to do if the commit is already reachable from the cursor;
+        // the index answers this without re-walking the DAG by hand
+        if self.oplog.index().is_ancestor(&sha, &self.cursor) {
+            return Err(format!("{} is already an ancestor of the cursor", sha));
+        }
+        // refuse to cherry-pick an operation whose dependencies are not live at
+        // the cursor, which would leave it orphaned
+        let live = self.live_ids_at(&self.cursor);
+        if let Some(missing) = operation.dependencies().iter().find(|d| !live.contains(*d)) {
+            return Err(format!(
+                "cherry-pick of {} would orphan: dependency '{}' is not present at the cursor",
+                sha, missing
+            ));
+        }
+        self.append(operation);
+        Ok(())
+    }
+
+    /// Reapply `commit` and all of its descendants onto `onto`, in dependency
+    /// order. Used to relocate a branch of the DAG; because commits are stored
+    /// in topological order, filtering preserves the order in which each
+    /// operation's dependencies are produced.
+    pub fn rebase(&mut self, commit: Sha, onto: Sha) -> Result<Sha, String> {
+        for sha in [&commit, &onto] {
+            if self.oplog.index().generation(sha).is_none() {
+                return Err(format!("SHA {} not found in oplog", sha));
+            }
+        }
+        let subtree = self.descendants_inclusive(&commit);
+        let operations: Vec<Operation> = self
+            .oplog
+            .commits
+            .iter()
+            .filter(|c| subtree.contains(&c.id))
+            .map(|c| c.operation.clone())
+            .collect();
+        // Refuse a rebase that would orphan an operation whose dependency is
+        // not live at `onto`, the way `cherry_pick` guards a single commit.
+        // Walk the operations in order, letting each one's produced id satisfy
+        // later ops in the same subtree.
+        let mut live = self.live_ids_at(&onto);
+        for operation in &operations {
+            if let Some(missing) = operation.dependencies().iter().find(|d| !live.contains(*d)) {
+                return Err(format!(
+                    "rebase onto {} would orphan: dependency '{}' is not present",
+                    onto, missing
+                ));
+            }
+            if let Some(id) = operation.produces() {
+                live.insert(id);
+            }
+        }
+        self.cursor = onto;
+        for operation in operations {
+            self.append(operation);
+        }
+        Ok(self.cursor.clone())
+    }
+
+    /// Undo a historical commit without rewriting history: compute the inverse
+    /// of its operation against the state as-of the reverted commit's parent and
+    /// append it as a new commit, git-revert style. Inverting against the parent
+    /// (rather than the cursor) is what lets `ModifyExtrusionDepth` restore the
+    /// depth that was in effect *before* the change: reading the post-change
+    /// state would recover the new depth and make the revert a no-op.
+    pub fn revert(&mut self, sha: Sha) -> Result<Sha, String> {
+        let commit = match self.oplog.commits.iter().find(|c| c.id == sha) {
+            Some(commit) => commit.clone(),
+            None => return Err(format!("SHA {} not found in oplog", sha)),
+        };
+        let parent_state = match commit.parents.first() {
+            Some(parent) => self.reconstruct(&parent.clone()),
+            None => State::new(),
+        };
+        match commit.operation.inverse(&parent_state) {
+            Some(inverse) => Ok(self.append(inverse)),
+            None => Err(format!("operation in {} is not invertible", sha)),
+        }
+    }
+
+    /// Flag every commit whose dependencies are not produced somewhere on its
+    /// own ancestry. Checking against the ids live at each commit (rather than a
+    /// single set accumulated over stored order) means a dependency satisfied
+    /// only on an unrelated branch does not count, as it must on a real DAG.
+    pub fn detect_orphans(&self) -> Vec<Sha> {
+        let mut orphans = vec![];
+        for commit in &self.oplog.commits {
+            let live = self.live_ids_at(&commit.id);
+            if !commit
+                .operation
+                .dependencies()
+                .iter()
+                .all(|d| live.contains(d))
+            {
+                orphans.push(commit.id.clone());
+            }
+        }
+        orphans
+    }
+
+    /// The set of ids produced along the ancestry of `sha` (inclusive).
+    fn live_ids_at(&self, sha: &Sha) -> HashSet<String> {
+        let ancestors = self.ancestors_inclusive(sha);
+        let mut produced = HashSet::new();
+        for commit in &self.oplog.commits {
+            if ancestors.contains(&commit.id) {
+                if let Some(id) = commit.operation.produces() {
+                    produced.insert(id);
+                }
+            }
+        }
+        produced
+    }
+
+    /// All ancestors of `sha` (inclusive), resolved through the reachability
+    /// index so orphan/merge-base checks share one source of ancestry.
+    fn ancestors_inclusive(&self, sha: &Sha) -> HashSet<Sha> {
+        self.oplog.index().ancestors_inclusive(sha)
+    }
+
+    /// All descendants of `sha` (inclusive), following child edges in the index.
+    fn descendants_inclusive(&self, sha: &Sha) -> HashSet<Sha> {
+        let mut set = HashSet::new();
+        let mut stack = vec![sha.clone()];
+        while let Some(node) = stack.pop() {
+            if set.insert(node.clone()) {
+                for child in self.oplog.index().children(&node) {
+                    stack.push(child.clone());
+                }
+            }
+        }
+        set
+    }
+
+    /// Reconstruct the evaluated project as of `sha`. Rather than replaying from
+    /// the init commit, start from the nearest snapshotted ancestor and replay
+    /// only the operations after it.
+    pub fn reconstruct(&self, sha: &Sha) -> State {
+        let (base, mut state) = match self.nearest_snapshot(sha) {
+            Some((base, state)) => (Some(base), state),
+            None => (None, State::new()),
+        };
+        let target = self.ancestors_inclusive(sha);
+        let already = base
+            .as_ref()
+            .map(|b| self.ancestors_inclusive(b))
+            .unwrap_or_default();
+        // commits are stored in topological order, so this applies the
+        // operations after the snapshot in dependency order
         for commit in &self.oplog.commits {
-            if commit.id == sha {
-                self.append(commit.operation.clone());
-                return Ok(());
+            if target.contains(&commit.id) && !already.contains(&commit.id) {
+                state.apply_operation(&commit.operation);
             }
         }
-        Err(format!("SHA {} not found in oplog", sha))
+        state
+    }
+
+    /// Reconstruct and cache the project state at the current cursor, then apply
+    /// the eviction policy.
+    pub fn snapshot(&mut self) {
+        let cursor = self.cursor.clone();
+        let state = self.reconstruct(&cursor);
+        self.snapshots.insert(cursor, state);
+        self.evict_snapshots();
+    }
+
+    /// The nearest snapshotted ancestor of `sha` (inclusive), found by a
+    /// breadth-first walk up the parent edges, together with its cached state.
+    pub fn nearest_snapshot(&self, sha: &Sha) -> Option<(Sha, State)> {
+        let by_id: HashMap<&Sha, &Commit> =
+            self.oplog.commits.iter().map(|c| (&c.id, c)).collect();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(sha.clone());
+        while let Some(node) = queue.pop_front() {
+            if !visited.insert(node.clone()) {
+                continue;
+            }
+            if let Some(state) = self.snapshots.get(&node) {
+                return Some((node.clone(), state.clone()));
+            }
+            if let Some(commit) = by_id.get(&node) {
+                queue.extend(commit.parents.iter().cloned());
+            }
+        }
+        None
+    }
+
+    /// Keep the `SNAPSHOT_KEEP_RECENT` newest snapshots (by generation) plus
+    /// exponentially spaced older ones, and drop the rest.
+    fn evict_snapshots(&mut self) {
+        let mut ordered: Vec<Sha> = self.snapshots.keys().cloned().collect();
+        ordered.sort_by_key(|s| {
+            std::cmp::Reverse(self.oplog.index().generation(s).unwrap_or(0))
+        });
+        let mut keep = HashSet::new();
+        for (rank, sha) in ordered.iter().enumerate() {
+            let older = rank.saturating_sub(SNAPSHOT_KEEP_RECENT);
+            if rank < SNAPSHOT_KEEP_RECENT || (older + 1).is_power_of_two() {
+                keep.insert(sha.clone());
+            }
+        }
+        self.snapshots.retain(|sha, _| keep.contains(sha));
     }
 }
 
@@ -106,8 +558,8 @@ impl EvolutionLog {
 pub struct Commit {
     pub operation: Operation,
     pub content_hash: Sha,
-    pub parent: Sha,
-    pub id: Sha, // this is the SHA of "operation + parent"
+    pub parents: Vec<Sha>, // empty for the init commit, two for a merge, one otherwise
+    pub id: Sha,           // this is the SHA of "operation + parents"
 }
 
 impl Commit {
@@ -115,12 +567,12 @@ impl Commit {
         let init_op = Operation::Create {
             nonce: "Hello World".to_string(), // TODO: replace with actual seeded random string
         };
-        let parent_sha = "".to_owned();
+        let parents = vec![];
         Self {
-            id: id_from_op_and_parent(&init_op, &parent_sha),
+            id: id_from_op_and_parents(&init_op, &parents),
             content_hash: init_op.hash(),
             operation: init_op,
-            parent: parent_sha,
+            parents,
         }
     }
 
@@ -128,6 +580,13 @@ impl Commit {
         // truncate to just the first 10 chars of self.id
         format!("{}: {}", &self.id[..10], self.operation.pretty_print())
     }
+
+    /// Recompute this commit's hashes from its contents and confirm they match
+    /// what is stored. A corrupted or hand-edited commit fails this check.
+    pub fn verify(&self) -> bool {
+        self.content_hash == self.operation.hash()
+            && self.id == id_from_op_and_parents(&self.operation, &self.parents)
+    }
 }
 
 pub type Sha = String;
@@ -175,6 +634,32 @@ pub enum Operation {
         unique_id: String,
         depth: f64,
     },
+    Merge {
+        parents: Vec<Sha>,
+        resolution: String,
+    },
+    DeletePlane {
+        name: String,
+    },
+    DeleteSketch {
+        unique_id: String,
+    },
+    DeleteRectangle {
+        sketch_id: String,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    DeleteCircle {
+        sketch_id: String,
+        x: f64,
+        y: f64,
+        radius: f64,
+    },
+    DeleteExtrusion {
+        unique_id: String,
+    },
 }
 
 impl Operation {
@@ -222,11 +707,117 @@ impl Operation {
             Operation::ModifyExtrusionDepth { unique_id, depth } => {
                 hasher.update(format!("{unique_id}-{depth}").as_bytes())
             }
+            Operation::Merge {
+                parents,
+                resolution,
+            } => hasher.update(format!("{}-{resolution}", parents.join(",")).as_bytes()),
+            Operation::DeletePlane { name } => hasher.update(name.as_bytes()),
+            Operation::DeleteSketch { unique_id } => hasher.update(unique_id.as_bytes()),
+            Operation::DeleteRectangle {
+                sketch_id,
+                x,
+                y,
+                width,
+                height,
+            } => hasher.update(format!("{sketch_id}-{x}-{y}-{width}-{height}").as_bytes()),
+            Operation::DeleteCircle {
+                sketch_id,
+                x,
+                y,
+                radius,
+            } => hasher.update(format!("{sketch_id}-{x}-{y}-{radius}").as_bytes()),
+            Operation::DeleteExtrusion { unique_id } => hasher.update(unique_id.as_bytes()),
         }
 
         format!("{:x}", hasher.finalize())
     }
 
+    /// The ids this operation reads, i.e. the entities that must already exist
+    /// for it to apply cleanly. An operation whose dependencies are not yet
+    /// produced by an earlier commit is an orphan (see
+    /// [`EvolutionLog::detect_orphans`]).
+    pub fn dependencies(&self) -> Vec<String> {
+        match self {
+            Operation::NewSketch { plane_name, .. } => vec![plane_name.clone()],
+            Operation::NewRectangle { sketch_id, .. } => vec![sketch_id.clone()],
+            Operation::NewCircle { sketch_id, .. } => vec![sketch_id.clone()],
+            Operation::NewExtrusion { sketch_id, .. } => vec![sketch_id.clone()],
+            Operation::ModifyExtrusionDepth { unique_id, .. } => vec![unique_id.clone()],
+            Operation::DeletePlane { name } => vec![name.clone()],
+            Operation::DeleteSketch { unique_id } => vec![unique_id.clone()],
+            Operation::DeleteRectangle { sketch_id, .. } => vec![sketch_id.clone()],
+            Operation::DeleteCircle { sketch_id, .. } => vec![sketch_id.clone()],
+            Operation::DeleteExtrusion { unique_id } => vec![unique_id.clone()],
+            Operation::Create { .. }
+            | Operation::Describe { .. }
+            | Operation::NewPlane { .. }
+            | Operation::Merge { .. } => vec![],
+        }
+    }
+
+    /// The id this operation creates, if any. Downstream operations may depend
+    /// on it.
+    pub fn produces(&self) -> Option<String> {
+        match self {
+            Operation::NewPlane { name, .. } => Some(name.clone()),
+            Operation::NewSketch { unique_id, .. } => Some(unique_id.clone()),
+            Operation::NewExtrusion { unique_id, .. } => Some(unique_id.clone()),
+            _ => None,
+        }
+    }
+
+    /// Produce the operation that cancels this one, git-revert style. `state`
+    /// must be the project *before* this operation was applied (i.e. at the
+    /// reverted commit's parent): creations invert to the matching `Delete*`
+    /// and ignore `state`, a depth change inverts to a change back to the depth
+    /// recorded in `state`, and operations with no meaningful inverse (including
+    /// the deletes themselves) return `None`.
+    pub fn inverse(&self, state: &Project) -> Option<Operation> {
+        match self {
+            Operation::NewPlane { name, .. } => Some(Operation::DeletePlane {
+                name: name.clone(),
+            }),
+            Operation::NewSketch { unique_id, .. } => Some(Operation::DeleteSketch {
+                unique_id: unique_id.clone(),
+            }),
+            Operation::NewRectangle {
+                sketch_id,
+                x,
+                y,
+                width,
+                height,
+            } => Some(Operation::DeleteRectangle {
+                sketch_id: sketch_id.clone(),
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            }),
+            Operation::NewCircle {
+                sketch_id,
+                x,
+                y,
+                radius,
+            } => Some(Operation::DeleteCircle {
+                sketch_id: sketch_id.clone(),
+                x: *x,
+                y: *y,
+                radius: *radius,
+            }),
+            Operation::NewExtrusion { unique_id, .. } => Some(Operation::DeleteExtrusion {
+                unique_id: unique_id.clone(),
+            }),
+            Operation::ModifyExtrusionDepth { unique_id, .. } => {
+                let previous = state.get_extrusion_depth(unique_id)?;
+                Some(Operation::ModifyExtrusionDepth {
+                    unique_id: unique_id.clone(),
+                    depth: previous,
+                })
+            }
+            _ => None,
+        }
+    }
+
     pub fn pretty_print(&self) -> String {
         match self {
             Operation::Create { nonce } => format!("Create: {}", nonce),
@@ -275,6 +866,99 @@ impl Operation {
             Operation::ModifyExtrusionDepth { unique_id, depth } => {
                 format!("ModifyExtrusionDepth: {} to {}", unique_id, depth)
             }
+            Operation::Merge { parents, .. } => {
+                format!("Merge: {}", parents.join(" + "))
+            }
+            Operation::DeletePlane { name } => format!("DeletePlane: '{}'", name),
+            Operation::DeleteSketch { unique_id } => format!("DeleteSketch: '{}'", unique_id),
+            Operation::DeleteRectangle {
+                sketch_id,
+                x,
+                y,
+                width,
+                height,
+            } => format!(
+                "DeleteRectangle: {} {} {} {} on '{}'",
+                x, y, width, height, sketch_id
+            ),
+            Operation::DeleteCircle {
+                sketch_id,
+                x,
+                y,
+                radius,
+            } => format!(
+                "DeleteCircle: ({},{}) radius: {} on '{}'",
+                x, y, radius, sketch_id
+            ),
+            Operation::DeleteExtrusion { unique_id } => {
+                format!("DeleteExtrusion: {}", unique_id)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A structure-only operation, used to build history shapes in tests
+    /// without depending on the geometry types an operation would otherwise
+    /// carry. The commit graph only cares about parent edges.
+    fn marker(tag: &str) -> Operation {
+        Operation::Describe {
+            description: tag.to_owned(),
+            commit: String::new(),
         }
     }
+
+    #[test]
+    fn common_ancestor_across_merge() {
+        // root ─ a ───────────┐
+        //   └── b1 ── b2 ── merge   (merge's first parent `a` is shallower
+        //                            than its second parent `b2`)
+        let mut log = EvolutionLog::new();
+        let root = log.cursor.clone();
+        let a = log.append(marker("a"));
+
+        log.checkout(root.clone()).unwrap();
+        let _b1 = log.append(marker("b1"));
+        let b2 = log.append(marker("b2"));
+
+        let merge = log.merge(a.clone(), b2.clone()).unwrap();
+
+        let index = log.oplog.index();
+        // Both parents of the merge are ancestors, including the second parent
+        // `b2` reached only through the non-first-parent edge.
+        assert!(index.is_ancestor(&a, &merge));
+        assert!(index.is_ancestor(&b2, &merge));
+        assert!(index.is_ancestor(&root, &merge));
+        // `b2` is its own merge base with the merge commit (it is an ancestor
+        // of the merge), and the two branch tips' base is the shared root.
+        assert_eq!(index.common_ancestor(&b2, &merge), Some(b2.clone()));
+        assert_eq!(index.common_ancestor(&a, &b2), Some(root));
+    }
+
+    #[test]
+    fn revert_of_depth_change_restores_previous_depth() {
+        let mut log = EvolutionLog::new();
+        log.append(Operation::NewExtrusion {
+            name: "ext".to_owned(),
+            unique_id: "ext".to_owned(),
+            sketch_id: "sketch".to_owned(),
+            click_x: 0.0,
+            click_y: 0.0,
+            depth: 5.0,
+        });
+        let modify = log.append(Operation::ModifyExtrusionDepth {
+            unique_id: "ext".to_owned(),
+            depth: 20.0,
+        });
+
+        // Reverting the depth change must append a change back to 5.0, not
+        // re-apply the current (20.0) depth.
+        log.revert(modify).unwrap();
+
+        let state = log.reconstruct(&log.cursor.clone());
+        assert_eq!(state.get_extrusion_depth("ext"), Some(5.0));
+    }
 }